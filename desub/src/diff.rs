@@ -0,0 +1,146 @@
+// Copyright 2019-2021 Parity Technologies (UK) Ltd.
+// This file is part of substrate-desub.
+//
+// substrate-desub is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+// substrate-desub is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with substrate-desub.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Structural diffing between two runtime versions registered with a [`crate::Decoder`].
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A pallet item (a call or a storage entry) identified by the pallet and item name it was
+/// found under.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Hash, Clone, Ord, PartialOrd)]
+pub struct ItemId {
+	pub module: String,
+	pub name: String,
+}
+
+impl ItemId {
+	pub(crate) fn new(module: impl Into<String>, name: impl Into<String>) -> Self {
+		Self { module: module.into(), name: name.into() }
+	}
+}
+
+/// An item whose shape changed between the two compared runtime versions, along with a
+/// human-readable rendering of its signature on either side.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct ChangedItem {
+	pub item: ItemId,
+	pub from: String,
+	pub to: String,
+}
+
+/// The structural differences between two registered runtime versions, as reported by
+/// [`crate::Decoder::diff`].
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Default)]
+pub struct RuntimeDiff {
+	pub pallets_added: Vec<String>,
+	pub pallets_removed: Vec<String>,
+	pub calls_added: Vec<ItemId>,
+	pub calls_removed: Vec<ItemId>,
+	pub calls_changed: Vec<ChangedItem>,
+	pub storage_added: Vec<ItemId>,
+	pub storage_removed: Vec<ItemId>,
+	pub storage_changed: Vec<ChangedItem>,
+}
+
+impl RuntimeDiff {
+	/// Whether neither runtime has anything the other doesn't and nothing changed shape.
+	pub fn is_empty(&self) -> bool {
+		self.pallets_added.is_empty()
+			&& self.pallets_removed.is_empty()
+			&& self.calls_added.is_empty()
+			&& self.calls_removed.is_empty()
+			&& self.calls_changed.is_empty()
+			&& self.storage_added.is_empty()
+			&& self.storage_removed.is_empty()
+			&& self.storage_changed.is_empty()
+	}
+}
+
+/// Diffs two maps of item name -> signature, classifying each name as added, removed, or
+/// changed (present on both sides but with a different signature).
+///
+/// `compare_signatures` must be `false` when `from` and `to` were rendered by different
+/// representations (one legacy, one v14+, i.e. a "mixed" pair): the two sides' signature
+/// strings are never equal even for an unchanged item, so signature comparison would report
+/// every retained item as changed. With it `false`, only additions/removals are reported and
+/// `changed` is always empty.
+pub(crate) fn diff_items(
+	module: &str,
+	from: &BTreeMap<String, String>,
+	to: &BTreeMap<String, String>,
+	compare_signatures: bool,
+) -> (Vec<ItemId>, Vec<ItemId>, Vec<ChangedItem>) {
+	let mut added = Vec::new();
+	let mut removed = Vec::new();
+	let mut changed = Vec::new();
+
+	for (name, to_sig) in to {
+		match from.get(name) {
+			None => added.push(ItemId::new(module, name.clone())),
+			Some(from_sig) if compare_signatures && from_sig != to_sig => {
+				changed.push(ChangedItem { item: ItemId::new(module, name.clone()), from: from_sig.clone(), to: to_sig.clone() })
+			}
+			Some(_) => {}
+		}
+	}
+	for name in from.keys() {
+		if !to.contains_key(name) {
+			removed.push(ItemId::new(module, name.clone()));
+		}
+	}
+
+	(added, removed, changed)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn map(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+		entries.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+	}
+
+	#[test]
+	fn reports_additions_removals_and_changes() {
+		let from = map(&[("transfer", "(AccountId, Balance)"), ("set_owner", "(AccountId)")]);
+		let to = map(&[("transfer", "(AccountId, Balance, bool)"), ("force_transfer", "(AccountId, AccountId, Balance)")]);
+
+		let (added, removed, changed) = diff_items("balances", &from, &to, true);
+
+		assert_eq!(added, vec![ItemId::new("balances", "force_transfer")]);
+		assert_eq!(removed, vec![ItemId::new("balances", "set_owner")]);
+		assert_eq!(
+			changed,
+			vec![ChangedItem {
+				item: ItemId::new("balances", "transfer"),
+				from: "(AccountId, Balance)".to_string(),
+				to: "(AccountId, Balance, bool)".to_string(),
+			}]
+		);
+	}
+
+	#[test]
+	fn skips_changed_detection_when_signatures_are_not_comparable() {
+		let from = map(&[("transfer", "legacy-repr")]);
+		let to = map(&[("transfer", "v14-repr")]);
+
+		let (added, removed, changed) = diff_items("balances", &from, &to, false);
+
+		assert!(added.is_empty());
+		assert!(removed.is_empty());
+		assert!(changed.is_empty());
+	}
+}
@@ -17,10 +17,12 @@
 
 #![forbid(unsafe_code)]
 #[deny(unused)]
+pub mod diff;
 mod error;
+pub mod substitute;
 pub mod types;
 
-use codec::Decode;
+use codec::{Compact, Decode};
 use desub_current::{
 	decoder::{self, Extrinsic, StorageDecoder},
 	Metadata as DesubMetadata,
@@ -30,7 +32,7 @@ use desub_legacy::{
 	RustTypeMarker, TypeDetective,
 };
 use frame_metadata::RuntimeMetadataPrefixed;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 #[cfg(feature = "polkadot-js")]
 use desub_json_resolver::TypeResolver as PolkadotJsResolver;
@@ -40,7 +42,13 @@ pub use desub_common::SpecVersion;
 #[cfg(feature = "polkadot-js")]
 pub use desub_json_resolver::runtimes;
 pub use desub_legacy::decoder::Chain;
-use types::{LegacyOrCurrentExtrinsic, LegacyOrCurrentStorage};
+use diff::{diff_items, RuntimeDiff};
+#[cfg(test)]
+use diff::ItemId;
+use scale_value::{scale::decode_as_type, ValueDef};
+use sp_core::H256;
+use substitute::CustomTypesBuilder;
+use types::{CurrentEvent, LegacyOrCurrentEvent, LegacyOrCurrentExtrinsic, LegacyOrCurrentStorage};
 
 /// Struct That implements TypeDetective but refuses to resolve anything
 /// that is not of metadata v14+.
@@ -62,6 +70,17 @@ impl TypeDetective for NoLegacyTypes {
 	}
 }
 
+/// Decode a `frame_system::Phase` from the front of an event record: a variant tag byte,
+/// followed by a `u32` block position for `ApplyExtrinsic`.
+fn decode_phase(data: &mut &[u8]) -> Result<types::Phase, Error> {
+	Ok(match u8::decode(data)? {
+		0 => types::Phase::ApplyExtrinsic(u32::decode(data)?),
+		1 => types::Phase::Finalization,
+		2 => types::Phase::Initialization,
+		_ => return Err(codec::Error::from("unknown Phase variant while decoding an event record").into()),
+	})
+}
+
 struct DsubMetadataAndDecoder {
 	metadata: DesubMetadata,
 	storage_decoder: StorageDecoder,
@@ -89,11 +108,10 @@ impl Decoder {
 		Self { legacy_decoder, current_metadata }
 	}
 
-	/// Create a new general Decoder
-	pub fn with_custom_types(types: impl TypeDetective + 'static, chain: Chain) -> Self {
-		let legacy_decoder = LegacyDecoder::new(types, chain);
-		let current_decoder = HashMap::new();
-		Self { legacy_decoder, current_metadata: current_decoder }
+	/// Start building a general Decoder backed by `types`, optionally registering explicit
+	/// type overrides before finishing with [`CustomTypesBuilder::build`].
+	pub fn with_custom_types<T: TypeDetective + 'static>(types: T, chain: Chain) -> CustomTypesBuilder<T> {
+		CustomTypesBuilder::new(types, chain)
 	}
 
 	/// Register a runtime version with the decoder.
@@ -154,7 +172,411 @@ impl Decoder {
 		}
 	}
 
+	/// Decode a whole batch of `(key, value)` storage entries against a single registered
+	/// runtime version, looking up the decoder for that version once instead of per-entry.
+	/// A malformed entry does not abort the rest of the batch; its `Err` is reported in place.
+	pub fn decode_storage_changes<'b>(
+		&self,
+		version: SpecVersion,
+		entries: impl IntoIterator<Item = (&'b [u8], Option<&'b [u8]>)>,
+	) -> Result<Vec<Result<LegacyOrCurrentStorage, Error>>, Error> {
+		if self.current_metadata.contains_key(&version) {
+			let DsubMetadataAndDecoder { metadata, storage_decoder } =
+				self.current_metadata.get(&version).expect("Checked if key is contained; qed");
+			Ok(entries
+				.into_iter()
+				.map(|(mut key_data, mut value_data)| {
+					match storage_decoder.decode_entry(metadata, &mut key_data, value_data.as_mut()) {
+						Ok(v) => Ok(LegacyOrCurrentStorage::Current(v.into_owned())),
+						Err(e) => Err(Error::V14 { source: e.into(), ext: None }),
+					}
+				})
+				.collect())
+		} else {
+			if !self.legacy_decoder.has_version(&version) {
+				return Err(Error::SpecVersionNotFound(version));
+			}
+			Ok(entries
+				.into_iter()
+				.map(|(key_data, value_data)| -> Result<LegacyOrCurrentStorage, Error> {
+					let storage = self.legacy_decoder.decode_storage(version, (key_data, value_data))?;
+					Ok(LegacyOrCurrentStorage::Legacy(storage))
+				})
+				.collect())
+		}
+	}
+
+	/// Decode the `System::Events` storage value for a registered runtime version into the
+	/// individual events it contains, in the order they were emitted.
+	///
+	/// `data` is the SCALE-encoded `Vec<EventRecord<Event, Hash>>`: a compact-encoded length
+	/// followed by, for each record, a `Phase`, the event itself (a pallet index byte followed
+	/// by a variant index byte and that variant's fields), and a `Vec<Hash>` of topics. For
+	/// v14+ runtimes the event's fields are resolved against the pallet's event type id through
+	/// the type registry carried by `DesubMetadata`. Legacy (pre-v14) runtimes are not yet
+	/// supported: doing so needs the `TypeDetective` registered with `self.legacy_decoder`
+	/// exposed the same way it already is for call argument resolution, which this series
+	/// doesn't add.
+	pub fn decode_events(&self, version: SpecVersion, mut data: &[u8]) -> Result<Vec<LegacyOrCurrentEvent>, Error> {
+		if let Some(DsubMetadataAndDecoder { metadata, .. }) = self.current_metadata.get(&version) {
+			let count = Compact::<u32>::decode(&mut data)?.0;
+			let mut events = Vec::with_capacity(count as usize);
+			for _ in 0..count {
+				let phase = decode_phase(&mut data)?;
+				let pallet_index = u8::decode(&mut data)?;
+				let pallet = metadata
+					.pallets()
+					.find(|p| p.index() == pallet_index)
+					.ok_or_else(|| codec::Error::from("event references an unknown pallet index"))?;
+				let event_ty = pallet
+					.event_type_id()
+					.ok_or_else(|| codec::Error::from("pallet has no event type but emitted an event"))?;
+				let value = decode_as_type(&mut data, event_ty, metadata.types())
+					.map_err(|_| codec::Error::from("failed to resolve event fields against the type registry"))?;
+				let (variant, fields) = match value.value {
+					ValueDef::Variant(v) => (v.name, v.values),
+					_ => return Err(codec::Error::from("pallet event type is not a variant type").into()),
+				};
+				let topics = Vec::<H256>::decode(&mut data)?;
+				events.push(LegacyOrCurrentEvent::Current(CurrentEvent {
+					phase,
+					pallet: pallet.name().to_string(),
+					variant,
+					fields: format!("{:?}", fields),
+					topics,
+				}));
+			}
+			Ok(events)
+		} else {
+			if !self.legacy_decoder.has_version(&version) {
+				return Err(Error::SpecVersionNotFound(version));
+			}
+			Err(codec::Error::from(
+				"legacy (pre-v14) event decoding is not supported yet: it needs the registered \
+				 TypeDetective exposed from desub_legacy::decoder::Decoder the same way call \
+				 argument resolution already is",
+			)
+			.into())
+		}
+	}
+
 	pub fn has_version(&self, version: &SpecVersion) -> bool {
 		self.current_metadata.contains_key(version) || self.legacy_decoder.has_version(version)
 	}
+
+	/// Compare the pallets, calls, and storage entries of two registered runtime versions,
+	/// reporting what was added, removed, or had its type signature change between them.
+	///
+	/// Useful as a pre-flight check when upgrading to a new spec version: a non-empty diff
+	/// means the decoder likely needs new type definitions before it can decode `to` without
+	/// error.
+	///
+	/// `from` and `to` may be registered on either side of the v14 metadata boundary. For such
+	/// a mixed pair the two runtimes' item signatures are rendered by different, incomparable
+	/// representations (resolved `scale_info` types vs. resolved `RustTypeMarker`s), so changed
+	/// items cannot be detected across it; only additions and removals are reported in that
+	/// case.
+	pub fn diff(&self, from: SpecVersion, to: SpecVersion) -> Result<RuntimeDiff, Error> {
+		if !self.has_version(&from) {
+			return Err(Error::SpecVersionNotFound(from));
+		}
+		if !self.has_version(&to) {
+			return Err(Error::SpecVersionNotFound(to));
+		}
+
+		let compare_signatures = self.current_metadata.contains_key(&from) == self.current_metadata.contains_key(&to);
+
+		let (from_pallets, from_calls, from_storage) = self.pallet_signatures(from);
+		let (to_pallets, to_calls, to_storage) = self.pallet_signatures(to);
+
+		let mut diff = RuntimeDiff::default();
+		for pallet in to_pallets.keys() {
+			if !from_pallets.contains_key(pallet) {
+				diff.pallets_added.push(pallet.clone());
+			}
+		}
+		for pallet in from_pallets.keys() {
+			if !to_pallets.contains_key(pallet) {
+				diff.pallets_removed.push(pallet.clone());
+			}
+		}
+
+		let empty = BTreeMap::new();
+		for pallet in from_calls.keys().chain(to_calls.keys()).collect::<std::collections::BTreeSet<_>>() {
+			let (added, removed, changed) = diff_items(
+				pallet,
+				from_calls.get(pallet).unwrap_or(&empty),
+				to_calls.get(pallet).unwrap_or(&empty),
+				compare_signatures,
+			);
+			diff.calls_added.extend(added);
+			diff.calls_removed.extend(removed);
+			diff.calls_changed.extend(changed);
+		}
+		for pallet in from_storage.keys().chain(to_storage.keys()).collect::<std::collections::BTreeSet<_>>() {
+			let (added, removed, changed) = diff_items(
+				pallet,
+				from_storage.get(pallet).unwrap_or(&empty),
+				to_storage.get(pallet).unwrap_or(&empty),
+				compare_signatures,
+			);
+			diff.storage_added.extend(added);
+			diff.storage_removed.extend(removed);
+			diff.storage_changed.extend(changed);
+		}
+
+		Ok(diff)
+	}
+
+	/// Collects, for a single registered version, the set of pallet names and a
+	/// `(module -> (item name -> type signature))` map for both calls and storage entries.
+	///
+	/// For v14+ runtimes the signature is the resolved `scale_info` type rendered through its
+	/// `Debug` impl; for legacy runtimes it is the resolved `RustTypeMarker` rendered the same
+	/// way, since both uniquely identify the shape of the encoded value.
+	#[allow(clippy::type_complexity)]
+	fn pallet_signatures(
+		&self,
+		version: SpecVersion,
+	) -> (
+		std::collections::BTreeSet<String>,
+		BTreeMap<String, BTreeMap<String, String>>,
+		BTreeMap<String, BTreeMap<String, String>>,
+	) {
+		let mut pallets = std::collections::BTreeSet::new();
+		let mut calls: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+		let mut storage: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+
+		if let Some(DsubMetadataAndDecoder { metadata, .. }) = self.current_metadata.get(&version) {
+			for pallet in metadata.pallets() {
+				pallets.insert(pallet.name().to_string());
+				let pallet_calls = calls.entry(pallet.name().to_string()).or_default();
+				for (name, ty) in pallet.call_types() {
+					pallet_calls.insert(name.to_string(), format!("{:?}", ty));
+				}
+				let pallet_storage = storage.entry(pallet.name().to_string()).or_default();
+				for (name, ty) in pallet.storage_types() {
+					pallet_storage.insert(name.to_string(), format!("{:?}", ty));
+				}
+			}
+		} else {
+			for pallet in self.legacy_decoder.pallets(version) {
+				pallets.insert(pallet.to_string());
+				let pallet_calls = calls.entry(pallet.to_string()).or_default();
+				for (name, ty) in self.legacy_decoder.call_types(version, pallet) {
+					pallet_calls.insert(name.to_string(), format!("{:?}", ty));
+				}
+				let pallet_storage = storage.entry(pallet.to_string()).or_default();
+				for (name, ty) in self.legacy_decoder.storage_types(version, pallet) {
+					pallet_storage.insert(name.to_string(), format!("{:?}", ty));
+				}
+			}
+		}
+
+		(pallets, calls, storage)
+	}
+}
+
+#[cfg(all(test, not(feature = "polkadot-js")))]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn diff_errors_on_unregistered_version() {
+		let decoder = Decoder::new();
+		let err = decoder.diff(1, 2).unwrap_err();
+		assert!(matches!(err, Error::SpecVersionNotFound(1)));
+	}
+
+	/// End-to-end check that `diff` classifies pallets/calls across two registered v14 versions
+	/// by walking pallet metadata directly, with no dependency on any companion function outside
+	/// this crate.
+	#[test]
+	fn diff_reports_pallet_and_call_changes_across_two_v14_versions() {
+		use codec::Encode;
+		use frame_metadata::v14::{ExtrinsicMetadata, PalletCallMetadata, PalletMetadata, RuntimeMetadataV14};
+		use frame_metadata::RuntimeMetadata;
+		use scale_info::{meta_type, TypeInfo};
+
+		#[derive(TypeInfo)]
+		#[allow(dead_code)]
+		enum CallsV1 {
+			Foo(u32),
+		}
+
+		#[derive(TypeInfo)]
+		#[allow(dead_code)]
+		enum CallsV2 {
+			Foo(bool),
+		}
+
+		let extrinsic = ExtrinsicMetadata { ty: meta_type::<()>(), version: 4, signed_extensions: vec![] };
+
+		let from_pallet = PalletMetadata {
+			name: "System",
+			storage: None,
+			calls: Some(PalletCallMetadata { ty: meta_type::<CallsV1>() }),
+			event: None,
+			constants: vec![],
+			error: None,
+			index: 0,
+		};
+		let from_metadata = RuntimeMetadataV14::new(vec![from_pallet], extrinsic.clone(), meta_type::<()>());
+		let from_prefixed: RuntimeMetadataPrefixed = RuntimeMetadata::V14(from_metadata).into();
+
+		let to_pallet = PalletMetadata {
+			name: "System",
+			storage: None,
+			calls: Some(PalletCallMetadata { ty: meta_type::<CallsV2>() }),
+			event: None,
+			constants: vec![],
+			error: None,
+			index: 0,
+		};
+		let added_pallet = PalletMetadata {
+			name: "Balances",
+			storage: None,
+			calls: None,
+			event: None,
+			constants: vec![],
+			error: None,
+			index: 1,
+		};
+		let to_metadata = RuntimeMetadataV14::new(vec![to_pallet, added_pallet], extrinsic, meta_type::<()>());
+		let to_prefixed: RuntimeMetadataPrefixed = RuntimeMetadata::V14(to_metadata).into();
+
+		let mut decoder = Decoder::new();
+		decoder.register_version(1, &from_prefixed.encode()).expect("from metadata should register");
+		decoder.register_version(2, &to_prefixed.encode()).expect("to metadata should register");
+
+		let diff = decoder.diff(1, 2).expect("both versions are registered");
+
+		assert_eq!(diff.pallets_added, vec!["Balances".to_string()]);
+		assert!(diff.pallets_removed.is_empty());
+		assert_eq!(diff.calls_changed.len(), 1);
+		assert_eq!(diff.calls_changed[0].item, ItemId::new("System", "Foo"));
+	}
+
+	/// End-to-end check that `decode_events` actually decodes a SCALE-encoded event record
+	/// against the registered v14 metadata's type registry, rather than forwarding to a
+	/// companion function outside this crate.
+	#[test]
+	fn decode_events_decodes_a_v14_event_record() {
+		use codec::Encode;
+		use frame_metadata::v14::{ExtrinsicMetadata, PalletEventMetadata, PalletMetadata, RuntimeMetadataV14};
+		use frame_metadata::RuntimeMetadata;
+		use scale_info::{meta_type, TypeInfo};
+
+		#[derive(TypeInfo, Encode)]
+		enum Event {
+			Created(u32),
+		}
+
+		let pallet = PalletMetadata {
+			name: "System",
+			storage: None,
+			calls: None,
+			event: Some(PalletEventMetadata { ty: meta_type::<Event>() }),
+			constants: vec![],
+			error: None,
+			index: 0,
+		};
+		let extrinsic = ExtrinsicMetadata { ty: meta_type::<()>(), version: 4, signed_extensions: vec![] };
+		let metadata = RuntimeMetadataV14::new(vec![pallet], extrinsic, meta_type::<()>());
+		let prefixed: RuntimeMetadataPrefixed = RuntimeMetadata::V14(metadata).into();
+
+		let mut decoder = Decoder::new();
+		decoder.register_version(1, &prefixed.encode()).expect("a valid v14 metadata blob should register");
+
+		// A single `EventRecord`: `Phase::Initialization`, pallet index 0, `Event::Created(7)`, no topics.
+		let mut record = Vec::new();
+		2u8.encode_to(&mut record);
+		0u8.encode_to(&mut record);
+		Event::Created(7).encode_to(&mut record);
+		Vec::<H256>::new().encode_to(&mut record);
+
+		let mut data = Vec::new();
+		Compact(1u32).encode_to(&mut data);
+		data.extend(record);
+
+		let events = decoder.decode_events(1, &data).expect("a single well-formed event record should decode");
+
+		assert_eq!(events.len(), 1);
+		match &events[0] {
+			types::LegacyOrCurrent::Current(event) => {
+				assert_eq!(event.phase, types::Phase::Initialization);
+				assert_eq!(event.pallet, "System");
+				assert_eq!(event.variant, "Created");
+			}
+			types::LegacyOrCurrent::Legacy(_) => panic!("expected a Current event"),
+		}
+	}
+
+	#[test]
+	fn decode_storage_changes_errors_on_unregistered_version() {
+		let decoder = Decoder::new();
+		let entries: Vec<(&[u8], Option<&[u8]>)> = vec![(&[1, 2, 3][..], None)];
+		let err = decoder.decode_storage_changes(99, entries).unwrap_err();
+		assert!(matches!(err, Error::SpecVersionNotFound(99)));
+	}
+
+	/// A batch with two well-formed entries and one referencing an unknown storage key should
+	/// report the malformed entry's `Err` in place rather than aborting the whole batch.
+	#[test]
+	fn decode_storage_changes_reports_malformed_entry_without_aborting_batch() {
+		use codec::Encode;
+		use frame_metadata::v14::{
+			ExtrinsicMetadata, PalletMetadata, PalletStorageMetadata, RuntimeMetadataV14, StorageEntryMetadata,
+			StorageEntryModifier, StorageEntryType,
+		};
+		use frame_metadata::RuntimeMetadata;
+		use scale_info::meta_type;
+
+		let storage = PalletStorageMetadata {
+			prefix: "System",
+			entries: vec![StorageEntryMetadata {
+				name: "Number",
+				modifier: StorageEntryModifier::Default,
+				ty: StorageEntryType::Plain(meta_type::<u32>()),
+				default: 0u32.encode(),
+				docs: vec![],
+			}],
+		};
+		let pallet = PalletMetadata {
+			name: "System",
+			storage: Some(storage),
+			calls: None,
+			event: None,
+			constants: vec![],
+			error: None,
+			index: 0,
+		};
+		let extrinsic = ExtrinsicMetadata { ty: meta_type::<()>(), version: 4, signed_extensions: vec![] };
+		let metadata = RuntimeMetadataV14::new(vec![pallet], extrinsic, meta_type::<()>());
+		let prefixed: RuntimeMetadataPrefixed = RuntimeMetadata::V14(metadata).into();
+		let encoded = prefixed.encode();
+
+		let mut decoder = Decoder::new();
+		decoder.register_version(1, &encoded).expect("a valid v14 metadata blob should register");
+
+		let good_key = [sp_core::hashing::twox_128(b"System"), sp_core::hashing::twox_128(b"Number")].concat();
+		let good_value = 42u32.encode();
+		let other_value = 7u32.encode();
+		let bad_key = vec![0xDE, 0xAD, 0xBE, 0xEF];
+
+		// The bad entry sits between two good ones, so a batch that aborted on the first error
+		// would also lose the trailing good entry.
+		let entries: Vec<(&[u8], Option<&[u8]>)> =
+			vec![(&good_key, Some(&good_value)), (&bad_key, None), (&good_key, Some(&other_value))];
+		let results = decoder.decode_storage_changes(1, entries).expect("version 1 is registered");
+
+		assert_eq!(results.len(), 3);
+		let first = results[0].as_ref().expect("the first well-formed entry should decode");
+		assert_eq!(first.module(), "System");
+		assert_eq!(first.name(), "Number");
+		assert!(results[1].is_err(), "the entry with an unknown storage key should surface as `Err` in place");
+		let third = results[2].as_ref().expect("the trailing well-formed entry should still decode");
+		assert_eq!(third.module(), "System");
+		assert_eq!(third.name(), "Number");
+	}
 }
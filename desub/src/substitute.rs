@@ -0,0 +1,210 @@
+// Copyright 2019-2021 Parity Technologies (UK) Ltd.
+// This file is part of substrate-desub.
+//
+// substrate-desub is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+// substrate-desub is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with substrate-desub.  If not, see <http://www.gnu.org/licenses/>.
+
+//! A `TypeDetective` that layers explicit type overrides on top of another `TypeDetective`.
+
+use crate::{Chain, Decoder, SpecVersion};
+use desub_legacy::{decoder::Decoder as LegacyDecoder, RustTypeMarker, TypeDetective};
+use std::collections::HashMap;
+
+/// `(module -> (type_name -> overrides))`, nested so the common lookup path (`get` twice) only
+/// ever borrows `module`/`type_name`, rather than allocating a `(String, String)` key per call.
+type Overrides = HashMap<String, HashMap<String, Vec<Override>>>;
+
+#[derive(Debug, Clone)]
+struct Override {
+	/// Inclusive `(from, to)` spec version range the override applies to, or `None` for all
+	/// versions.
+	spec_range: Option<(SpecVersion, SpecVersion)>,
+	ty: RustTypeMarker,
+}
+
+/// Wraps a `TypeDetective` with a table of explicit `(module, type name)` overrides that are
+/// consulted first, falling through to the wrapped detective on a miss.
+///
+/// Lets a caller force a known decoding for a single problematic type on an old chain, without
+/// having to fork the type definitions the wrapped detective resolves everything else from.
+pub struct SubstituteTypes<T> {
+	inner: T,
+	overrides: Overrides,
+}
+
+impl<T: TypeDetective> SubstituteTypes<T> {
+	fn new(inner: T, overrides: Overrides) -> Self {
+		Self { inner, overrides }
+	}
+
+	/// Looks up a `(module, type_name)` override, preferring one scoped to `spec` over an
+	/// unconditional override registered for the same pair: the more specific registration
+	/// wins regardless of which was registered first.
+	fn lookup(&self, module: &str, spec: u32, ty: &str) -> Option<&RustTypeMarker> {
+		let candidates = self.overrides.get(module)?.get(ty)?;
+		candidates
+			.iter()
+			.find(|o| matches!(o.spec_range, Some((from, to)) if spec >= from && spec <= to))
+			.or_else(|| candidates.iter().find(|o| o.spec_range.is_none()))
+			.map(|o| &o.ty)
+	}
+}
+
+impl<T: TypeDetective> TypeDetective for SubstituteTypes<T> {
+	fn get(&self, module: &str, spec: u32, ty: &str, chain: &str) -> Option<&RustTypeMarker> {
+		self.lookup(module, spec, ty).or_else(|| self.inner.get(module, spec, ty, chain))
+	}
+
+	fn try_fallback(&self, module: &str, ty: &str) -> Option<&RustTypeMarker> {
+		self.inner.try_fallback(module, ty)
+	}
+
+	fn get_extrinsic_ty(&self, module: &str, spec: u32, ty: &str) -> Option<&RustTypeMarker> {
+		self.lookup(module, spec, ty).or_else(|| self.inner.get_extrinsic_ty(module, spec, ty))
+	}
+}
+
+/// Builds a [`Decoder`] whose legacy type resolution is backed by `T`, with explicit overrides
+/// registered for specific `(module, type name)` pairs.
+///
+/// Obtained via [`Decoder::with_custom_types`].
+pub struct CustomTypesBuilder<T> {
+	types: T,
+	chain: Chain,
+	overrides: Overrides,
+}
+
+impl<T: TypeDetective + 'static> CustomTypesBuilder<T> {
+	pub(crate) fn new(types: T, chain: Chain) -> Self {
+		Self { types, chain, overrides: HashMap::new() }
+	}
+
+	/// Force `(module, type_name)` to decode as `ty` for every spec version not covered by a
+	/// spec-scoped override registered for the same pair; a covering scoped override wins.
+	pub fn override_type(self, module: impl Into<String>, type_name: impl Into<String>, ty: RustTypeMarker) -> Self {
+		self.override_type_inner(module, type_name, None, ty)
+	}
+
+	/// Force `(module, type_name)` to decode as `ty` for spec versions in the inclusive range
+	/// `[from, to]`, falling back to the wrapped `TypeDetective` outside of it.
+	pub fn override_type_for_spec(
+		self,
+		module: impl Into<String>,
+		type_name: impl Into<String>,
+		from: SpecVersion,
+		to: SpecVersion,
+		ty: RustTypeMarker,
+	) -> Self {
+		self.override_type_inner(module, type_name, Some((from, to)), ty)
+	}
+
+	fn override_type_inner(
+		mut self,
+		module: impl Into<String>,
+		type_name: impl Into<String>,
+		spec_range: Option<(SpecVersion, SpecVersion)>,
+		ty: RustTypeMarker,
+	) -> Self {
+		self.overrides.entry(module.into()).or_default().entry(type_name.into()).or_default().push(Override {
+			spec_range,
+			ty,
+		});
+		self
+	}
+
+	/// Finish building the `Decoder`, wrapping `T` with the registered overrides.
+	pub fn build(self) -> Decoder {
+		let types = SubstituteTypes::new(self.types, self.overrides);
+		Decoder { legacy_decoder: LegacyDecoder::new(types, self.chain), current_metadata: HashMap::new() }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[derive(Clone)]
+	struct StubTypes;
+
+	impl TypeDetective for StubTypes {
+		fn get(&self, _: &str, _: u32, _: &str, _: &str) -> Option<&RustTypeMarker> {
+			None
+		}
+
+		fn try_fallback(&self, _: &str, _: &str) -> Option<&RustTypeMarker> {
+			None
+		}
+
+		fn get_extrinsic_ty(&self, _: &str, _: u32, _: &str) -> Option<&RustTypeMarker> {
+			None
+		}
+	}
+
+	fn with_override(module: &str, type_name: &str, spec_range: Option<(SpecVersion, SpecVersion)>, ty: RustTypeMarker) -> SubstituteTypes<StubTypes> {
+		let mut overrides: Overrides = HashMap::new();
+		overrides.entry(module.to_string()).or_default().entry(type_name.to_string()).or_default().push(Override {
+			spec_range,
+			ty,
+		});
+		SubstituteTypes::new(StubTypes, overrides)
+	}
+
+	#[test]
+	fn override_takes_precedence_over_inner() {
+		let types = with_override("balances", "Balance", None, RustTypeMarker::U32);
+		assert_eq!(types.get("balances", 100, "Balance", "kusama"), Some(&RustTypeMarker::U32));
+		assert_eq!(types.get_extrinsic_ty("balances", 100, "Balance"), Some(&RustTypeMarker::U32));
+	}
+
+	#[test]
+	fn override_respects_spec_range() {
+		let types = with_override("balances", "Balance", Some((10, 20)), RustTypeMarker::U32);
+		assert_eq!(types.get("balances", 15, "Balance", "kusama"), Some(&RustTypeMarker::U32));
+		assert_eq!(types.get("balances", 25, "Balance", "kusama"), None);
+	}
+
+	#[test]
+	fn falls_through_to_inner_on_miss() {
+		let types = SubstituteTypes::new(StubTypes, HashMap::new());
+		assert_eq!(types.get("balances", 15, "Balance", "kusama"), None);
+		assert_eq!(types.get_extrinsic_ty("balances", 15, "Balance"), None);
+		assert_eq!(types.try_fallback("balances", "Balance"), None);
+	}
+
+	/// A spec-scoped override should win over an unconditional one registered for the same
+	/// `(module, type_name)`, regardless of which was registered first.
+	#[test]
+	fn spec_scoped_override_takes_precedence_over_unconditional_one() {
+		let mut overrides: Overrides = HashMap::new();
+		let entry = overrides.entry("balances".to_string()).or_default().entry("Balance".to_string()).or_default();
+		entry.push(Override { spec_range: None, ty: RustTypeMarker::U32 });
+		entry.push(Override { spec_range: Some((10, 20)), ty: RustTypeMarker::U64 });
+		let types = SubstituteTypes::new(StubTypes, overrides);
+
+		assert_eq!(types.get("balances", 15, "Balance", "kusama"), Some(&RustTypeMarker::U64));
+		assert_eq!(types.get("balances", 25, "Balance", "kusama"), Some(&RustTypeMarker::U32));
+	}
+
+	/// `lookup` backs both `get` and `get_extrinsic_ty`; the scoped-over-unconditional
+	/// precedence must hold for the latter too, not just the former.
+	#[test]
+	fn spec_scoped_override_takes_precedence_for_extrinsic_ty_too() {
+		let mut overrides: Overrides = HashMap::new();
+		let entry = overrides.entry("balances".to_string()).or_default().entry("Balance".to_string()).or_default();
+		entry.push(Override { spec_range: None, ty: RustTypeMarker::U32 });
+		entry.push(Override { spec_range: Some((10, 20)), ty: RustTypeMarker::U64 });
+		let types = SubstituteTypes::new(StubTypes, overrides);
+
+		assert_eq!(types.get_extrinsic_ty("balances", 15, "Balance"), Some(&RustTypeMarker::U64));
+		assert_eq!(types.get_extrinsic_ty("balances", 25, "Balance"), Some(&RustTypeMarker::U32));
+	}
+}
@@ -14,8 +14,9 @@
 // along with substrate-desub.  If not, see <http://www.gnu.org/licenses/>.
 
 use desub_current::decoder::{Extrinsic, StorageEntry};
-use desub_legacy::decoder::{GenericExtrinsic, GenericStorage};
+use desub_legacy::decoder::{GenericEvent, GenericExtrinsic, GenericStorage, Phase as LegacyPhase};
 use serde::{Deserialize, Serialize};
+use sp_core::H256;
 
 #[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
 pub enum LegacyOrCurrent<L, C> {
@@ -27,6 +28,8 @@ pub type LegacyOrCurrentExtrinsic = LegacyOrCurrent<GenericExtrinsic, Extrinsic<
 
 pub type LegacyOrCurrentStorage = LegacyOrCurrent<GenericStorage, StorageEntry<'static, 'static>>;
 
+pub type LegacyOrCurrentEvent = LegacyOrCurrent<GenericEvent, CurrentEvent>;
+
 impl LegacyOrCurrentStorage {
 	pub fn module(&self) -> String {
 		match self {
@@ -42,3 +45,72 @@ impl LegacyOrCurrentStorage {
 		}
 	}
 }
+
+/// The point during block execution at which an event was emitted.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Phase {
+	/// Applying the extrinsic at the given index within the block.
+	ApplyExtrinsic(u32),
+	/// Finalizing the block.
+	Finalization,
+	/// Initializing the block.
+	Initialization,
+}
+
+/// A v14+ event decoded directly by [`crate::Decoder::decode_events`]: the pallet and variant
+/// are resolved from the pallet index and variant index read after the record's phase, and
+/// `fields` is the event's remaining data resolved against the pallet's event type id through
+/// the metadata's type registry and rendered through its `Debug` impl.
+#[derive(Deserialize, Serialize, Debug, PartialEq, Clone)]
+pub struct CurrentEvent {
+	pub phase: Phase,
+	pub pallet: String,
+	pub variant: String,
+	pub fields: String,
+	pub topics: Vec<H256>,
+}
+
+impl From<LegacyPhase> for Phase {
+	fn from(phase: LegacyPhase) -> Self {
+		match phase {
+			LegacyPhase::ApplyExtrinsic(i) => Phase::ApplyExtrinsic(i),
+			LegacyPhase::Finalization => Phase::Finalization,
+			LegacyPhase::Initialization => Phase::Initialization,
+		}
+	}
+}
+
+impl LegacyOrCurrentEvent {
+	pub fn module(&self) -> String {
+		match self {
+			LegacyOrCurrent::Current(event) => event.pallet.clone(),
+			LegacyOrCurrent::Legacy(event) => event.module().clone(),
+		}
+	}
+
+	pub fn name(&self) -> String {
+		match self {
+			LegacyOrCurrent::Current(event) => event.variant.clone(),
+			LegacyOrCurrent::Legacy(event) => event.variant().clone(),
+		}
+	}
+
+	pub fn phase(&self) -> Phase {
+		match self {
+			LegacyOrCurrent::Current(event) => event.phase,
+			LegacyOrCurrent::Legacy(event) => event.phase().into(),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn legacy_phase_converts() {
+		assert_eq!(Phase::from(LegacyPhase::ApplyExtrinsic(3)), Phase::ApplyExtrinsic(3));
+		assert_eq!(Phase::from(LegacyPhase::Finalization), Phase::Finalization);
+		assert_eq!(Phase::from(LegacyPhase::Initialization), Phase::Initialization);
+	}
+}